@@ -1,24 +1,26 @@
-// `RefCell<std::string::String>` cannot be shared between threads safely
-// within `RjvParams`, the trait `Sync` is not implemented for `RefCell<std::string::String>`
-// (if you want to do aliasing and mutation between multiple threads, use `std::sync::RwLock` insteadrustcClick for full compiler diagnostic)
-//
-// ===
+//! String parameters.
 
-//! Stepped integer parameters.
-
-// use atomic_float::AtomicF32;
+use arc_swap::ArcSwap;
 use std::fmt::{Debug, Display};
-// use std::sync::atomic::{AtomicI32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use super::internals::ParamPtr;
 use super::{Param, ParamFlags, ParamMut};
 
-/// A discrete integer parameter that's stored unnormalized. The range is used for the normalization
-/// process.
+/// A parameter that's stored as an arbitrary string. By default this is hidden and
+/// non-automatable free-form text, since hosts have no sensible way to automate or display
+/// arbitrary strings. Call [`with_variants()`][Self::with_variants()] to constrain it to a fixed
+/// list of strings instead, which turns it into an automatable, enum-like choice parameter.
+///
+/// Like every other [`Param`], this is read and written through its plain value
+/// ([`Param::default_plain_value()`], [`ParamMut::set_plain_value()`]), which is what NIH-plug's
+/// state save/restore and preset recall go through. Its `Self::Plain = String`, so whatever state
+/// persistence does with a param's plain value applies here too, without any special-casing.
 pub struct StringParam {
-    /// The field's current plain value, after monophonic modulation has been applied.
-    value: Arc<Mutex<String>>,
+    /// The field's current plain value, after monophonic modulation has been applied. Stored as
+    /// an atomically swapped `Arc` so the audio thread can read it with a wait-free load plus an
+    /// `Arc` clone, instead of potentially blocking on a GUI thread holding a lock.
+    value: ArcSwap<String>,
 
     default: String,
 
@@ -48,13 +50,18 @@ pub struct StringParam {
     ///
     /// The input string may or may not contain the unit, so you will need to be able to handle
     /// that.
-    #[allow(unused)]
-    string_to_value: Option<Arc<dyn Fn(&String) -> Option<i32> + Send + Sync>>,
+    string_to_value: Option<Arc<dyn Fn(&str) -> Option<String> + Send + Sync>>,
+
+    /// When set, this parameter is constrained to this fixed, ordered list of strings. The
+    /// parameter then behaves like an enum: its normalized value is derived from the index of the
+    /// current value in this list, which allows hosts to automate it and display it as a discrete
+    /// choice instead of free-form text.
+    variants: Option<Vec<String>>,
 }
 
 impl Display for StringParam {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.value.as_ref().lock().unwrap().clone())
+        write!(f, "{}", self.value.load())
     }
 }
 
@@ -85,22 +92,22 @@ impl Param for StringParam {
 
     #[inline]
     fn modulated_plain_value(&self) -> Self::Plain {
-        self.value.as_ref().lock().unwrap().clone()
+        self.value.load_full().as_ref().clone()
     }
 
     #[inline]
     fn modulated_normalized_value(&self) -> f32 {
-        0.0
+        self.preview_normalized(self.modulated_plain_value())
     }
 
     #[inline]
     fn unmodulated_plain_value(&self) -> Self::Plain {
-        self.value.as_ref().lock().unwrap().clone()
+        self.value.load_full().as_ref().clone()
     }
 
     #[inline]
     fn unmodulated_normalized_value(&self) -> f32 {
-        0.0
+        self.preview_normalized(self.unmodulated_plain_value())
     }
 
     #[inline]
@@ -109,15 +116,27 @@ impl Param for StringParam {
     }
 
     fn step_count(&self) -> Option<usize> {
-        None
+        self.variants.as_ref().map(|variants| variants.len() - 1)
     }
 
-    fn previous_step(&self, _from: Self::Plain, _finer: bool) -> Self::Plain {
-        self.value.as_ref().lock().unwrap().clone()
+    fn previous_step(&self, from: Self::Plain, _finer: bool) -> Self::Plain {
+        match &self.variants {
+            Some(variants) => {
+                let index = Self::index_of(variants, &from).unwrap_or(0);
+                variants[index.saturating_sub(1)].clone()
+            }
+            None => from,
+        }
     }
 
-    fn next_step(&self, _from: Self::Plain, _finer: bool) -> Self::Plain {
-        self.value.as_ref().lock().unwrap().clone()
+    fn next_step(&self, from: Self::Plain, _finer: bool) -> Self::Plain {
+        match &self.variants {
+            Some(variants) => {
+                let index = Self::index_of(variants, &from).unwrap_or(0);
+                variants[(index + 1).min(variants.len() - 1)].clone()
+            }
+            None => from,
+        }
     }
 
     fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String {
@@ -130,18 +149,40 @@ impl Param for StringParam {
         }
     }
 
-    fn string_to_normalized_value(&self, _string: &str) -> Option<f32> {
-        None
+    fn string_to_normalized_value(&self, string: &str) -> Option<f32> {
+        let plain = self.string_to_plain(string)?;
+
+        // There's no normalized encoding for an arbitrary free-form string, so there's no way to
+        // hand the parsed value back for the caller to apply through `set_normalized_value()`
+        // later without relying on an undocumented, racy call-order side channel. Apply it here
+        // instead, and return a stable normalized value: the caller's guaranteed-to-follow
+        // `set_normalized_value()` call then just re-applies the same plain value, which is a
+        // harmless no-op.
+        self.set_plain_value(plain);
+        Some(self.unmodulated_normalized_value())
     }
 
     #[inline]
-    fn preview_normalized(&self, _plain: Self::Plain) -> f32 {
-        0.0
+    fn preview_normalized(&self, plain: Self::Plain) -> f32 {
+        match &self.variants {
+            Some(variants) => {
+                let index = Self::index_of(variants, &plain).unwrap_or(0);
+                Self::normalized_from_index(index, variants.len())
+            }
+            None => 0.0,
+        }
     }
 
     #[inline]
-    fn preview_plain(&self, _normalized: f32) -> Self::Plain {
-        self.value.as_ref().lock().unwrap().clone()
+    fn preview_plain(&self, normalized: f32) -> Self::Plain {
+        match &self.variants {
+            Some(variants) => {
+                let index = ((normalized * (variants.len() - 1) as f32).round() as usize)
+                    .min(variants.len() - 1);
+                variants[index].clone()
+            }
+            None => self.value.load_full().as_ref().clone(),
+        }
     }
 
     fn flags(&self) -> ParamFlags {
@@ -155,14 +196,13 @@ impl Param for StringParam {
 
 impl ParamMut for StringParam {
     fn set_plain_value(&self, plain: Self::Plain) -> bool {
-        if self.value() != plain {
-            let mut h = self.value.lock().unwrap();
-            *h = plain;
+        self.debug_assert_known_variant(&plain);
 
-            // self.value = plain.clone(); // WHAT
+        if self.value() != plain {
+            self.value.store(Arc::new(plain));
 
             if let Some(f) = &self.value_changed {
-                f(self.value.as_ref().lock().unwrap().clone());
+                f(self.value.load_full().as_ref().clone());
             }
             true
         } else {
@@ -197,7 +237,7 @@ impl StringParam {
     pub fn new(name: impl Into<String>, default: String) -> Self {
         Self {
             default: default.clone(),
-            value: Arc::new(Mutex::new(default)),
+            value: ArcSwap::new(Arc::new(default)),
 
             flags: ParamFlags::default()
                 .union(ParamFlags::HIDDEN)
@@ -209,18 +249,69 @@ impl StringParam {
             unit: "",
             value_to_string: None,
             string_to_value: None,
+            variants: None,
+        }
+    }
+
+    /// Returns the index of `value` in `variants`, if present.
+    fn index_of(variants: &[String], value: &str) -> Option<usize> {
+        variants.iter().position(|variant| variant == value)
+    }
+
+    /// Derive a normalized value from a variant's index in a list of `n` variants.
+    fn normalized_from_index(index: usize, n: usize) -> f32 {
+        if n <= 1 {
+            0.0
+        } else {
+            index as f32 / (n - 1) as f32
+        }
+    }
+
+    /// Panic in debug builds (through `debug_assert!()`) when `with_variants()` is used but `plain`
+    /// is not one of the allowed variants. This can happen when `set_value()`/`set_plain_value()`
+    /// is called directly instead of going through [`string_to_plain()`][Self::string_to_plain()].
+    /// Release builds skip this check: `index_of()` then can't find the value and silently falls
+    /// back to index 0, so the parameter will redisplay and report its normalized/step position
+    /// as the first variant instead of `plain`.
+    fn debug_assert_known_variant(&self, plain: &str) {
+        if let Some(variants) = &self.variants {
+            debug_assert!(
+                Self::index_of(variants, plain).is_some(),
+                "StringParam's value was set to {plain:?}, which is not in its `with_variants()` \
+                 list. It will display and report its normalized/step position as the first \
+                 variant instead."
+            );
+        }
+    }
+
+    /// Parse a user- or host-provided string into a plain value, used both by
+    /// [`string_to_normalized_value()`][Param::string_to_normalized_value()] and by
+    /// [`set_value_from_string()`][Self::set_value_from_string()]. The input string may or may not
+    /// contain the unit. Returns `None` if the string could not be parsed.
+    fn string_to_plain(&self, string: &str) -> Option<String> {
+        let string = string.strip_suffix(self.unit).unwrap_or(string);
+
+        if let Some(variants) = &self.variants {
+            return variants
+                .iter()
+                .find(|variant| variant.as_str() == string)
+                .cloned();
+        }
+
+        match &self.string_to_value {
+            Some(f) => f(string),
+            None => Some(string.to_owned()),
         }
     }
 
     pub fn set_value(&self, plain: String) -> bool {
-        if self.value() != plain {
-            let mut h = self.value.lock().unwrap();
-            *h = plain;
+        self.debug_assert_known_variant(&plain);
 
-            // self.value = plain.clone(); // WHAT
+        if self.value() != plain {
+            self.value.store(Arc::new(plain));
 
             if let Some(f) = &self.value_changed {
-                f(self.value.as_ref().lock().unwrap().clone());
+                f(self.value.load_full().as_ref().clone());
             }
             true
         } else {
@@ -240,6 +331,21 @@ impl StringParam {
         self.modulated_plain_value() // kept
     }
 
+    /// Set this parameter's value from a user-provided string, going through
+    /// [`with_string_to_value()`][Self::with_string_to_value()]'s callback (or the variant list
+    /// set by [`with_variants()`][Self::with_variants()]) if one is set. A convenience for calling
+    /// into this parameter directly from the plugin's own GUI or preset-import code; hosts go
+    /// through [`string_to_normalized_value()`][Param::string_to_normalized_value()] and
+    /// [`set_normalized_value()`][ParamMut::set_normalized_value()] instead, via `ParamPtr`.
+    /// Returns `false` without modifying the value if the string could not be parsed, canceling
+    /// the update.
+    pub fn set_value_from_string(&self, string: &str) -> bool {
+        match self.string_to_plain(string) {
+            Some(plain) => self.set_value(plain),
+            None => false,
+        }
+    }
+
     // /// Enable polyphonic modulation for this parameter. The ID is used to uniquely identify this
     // /// parameter in [`NoteEvent::PolyModulation`][crate::prelude::NoteEvent::PolyModulation]
     // /// events, and must thus be unique between _all_ polyphonically modulatable parameters. See the
@@ -306,19 +412,50 @@ impl StringParam {
 
     // `with_step_size` is only implemented for the f32 version
 
-    // /// Use a custom conversion function to convert from a string to a plain, unnormalized
-    // /// value. If the string cannot be parsed, then this should return a `None`. If this
-    // /// happens while the parameter is being updated then the update will be canceled.
-    // ///
-    // /// The input string may or may not contain the unit, so you will need to be able to handle
-    // /// that.
-    // pub fn with_string_to_value(
-    //     mut self,
-    //     callback: Arc<dyn Fn(&str) -> Option<String> + Send + Sync>,
-    // ) -> Self {
-    //     self.string_to_value = Some(callback);
-    //     self
-    // }
+    /// Constrain this parameter to a fixed, ordered list of strings, turning it into an
+    /// enum-style choice parameter. The normalized value is then derived from the variant's
+    /// index in this list, which also lets the host automate this parameter and display it as a
+    /// discrete choice instead of free-form text. This also removes the [`HIDDEN`] and
+    /// [`NON_AUTOMATABLE`] flags that are otherwise forced on free-form `StringParam`s.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `variants` is empty, or if it does not contain the parameter's default value.
+    ///
+    /// [`HIDDEN`]: ParamFlags::HIDDEN
+    /// [`NON_AUTOMATABLE`]: ParamFlags::NON_AUTOMATABLE
+    pub fn with_variants(mut self, variants: impl Into<Vec<String>>) -> Self {
+        let variants = variants.into();
+        assert!(
+            !variants.is_empty(),
+            "`with_variants()` needs at least one variant"
+        );
+        assert!(
+            variants.contains(&self.default),
+            "`with_variants()`'s variant list must contain the parameter's default value"
+        );
+
+        self.flags
+            .remove(ParamFlags::HIDDEN | ParamFlags::NON_AUTOMATABLE);
+        self.variants = Some(variants);
+
+        self
+    }
+
+    /// Use a custom conversion function to convert from a string to a plain, unnormalized
+    /// value. If the string cannot be parsed, then this should return a `None`. If this
+    /// happens while the parameter is being updated then the update will be canceled.
+    ///
+    /// The input string may or may not contain the unit, so you will need to be able to handle
+    /// that. This has no effect when [`with_variants()`][Self::with_variants()] is used, since
+    /// the variant list is then the only source of truth for parsing.
+    pub fn with_string_to_value(
+        mut self,
+        callback: Arc<dyn Fn(&str) -> Option<String> + Send + Sync>,
+    ) -> Self {
+        self.string_to_value = Some(callback);
+        self
+    }
 
     /// Mark the parameter as non-automatable. This means that the parameter cannot be changed from
     /// an automation lane. The parameter can however still be manually changed by the user from
@@ -343,3 +480,91 @@ impl StringParam {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants_param() -> StringParam {
+        StringParam::new("Mode", String::from("a")).with_variants(vec![
+            String::from("a"),
+            String::from("b"),
+            String::from("c"),
+        ])
+    }
+
+    #[test]
+    fn with_variants_steps_and_clamps_at_the_edges() {
+        let param = variants_param();
+
+        assert_eq!(param.step_count(), Some(2));
+
+        assert_eq!(param.next_step(String::from("a"), false), "b");
+        assert_eq!(param.next_step(String::from("c"), false), "c");
+
+        assert_eq!(param.previous_step(String::from("c"), false), "b");
+        assert_eq!(param.previous_step(String::from("a"), false), "a");
+    }
+
+    #[test]
+    fn with_variants_derives_normalized_value_from_index() {
+        let param = variants_param();
+
+        assert_eq!(param.preview_normalized(String::from("a")), 0.0);
+        assert_eq!(param.preview_normalized(String::from("b")), 0.5);
+        assert_eq!(param.preview_normalized(String::from("c")), 1.0);
+
+        assert_eq!(param.preview_plain(0.0), "a");
+        assert_eq!(param.preview_plain(0.1), "a");
+        assert_eq!(param.preview_plain(0.5), "b");
+        assert_eq!(param.preview_plain(0.9), "c");
+        assert_eq!(param.preview_plain(1.0), "c");
+    }
+
+    #[test]
+    fn a_single_variant_has_no_steps_and_is_always_normalized_to_zero() {
+        let param = StringParam::new("Mode", String::from("only"))
+            .with_variants(vec![String::from("only")]);
+
+        assert_eq!(param.step_count(), Some(0));
+        assert_eq!(param.preview_normalized(String::from("only")), 0.0);
+        assert_eq!(param.preview_plain(1.0), "only");
+    }
+
+    #[test]
+    fn string_to_normalized_value_round_trips_through_set_normalized_value_with_variants() {
+        let param = variants_param();
+
+        let normalized = param
+            .string_to_normalized_value("b")
+            .expect("b is a known variant");
+
+        // `string_to_normalized_value()` already applied "b"; the following `set_normalized_value()`
+        // call (as a real caller would make) must be a harmless no-op that doesn't change it.
+        assert_eq!(param.value(), "b");
+        assert!(!param.set_normalized_value(normalized));
+        assert_eq!(param.value(), "b");
+    }
+
+    #[test]
+    fn string_to_normalized_value_round_trips_through_set_normalized_value_free_form() {
+        let param = StringParam::new("Name", String::from("default"));
+
+        let normalized = param
+            .string_to_normalized_value("a typed value")
+            .expect("free-form StringParams accept any string by default");
+
+        // The value is already applied by `string_to_normalized_value()` itself; the following
+        // `set_normalized_value()` call (as a real caller would make) must be a harmless no-op.
+        assert_eq!(param.value(), "a typed value");
+        assert!(!param.set_normalized_value(normalized));
+        assert_eq!(param.value(), "a typed value");
+    }
+
+    #[test]
+    fn string_to_normalized_value_rejects_unknown_variants() {
+        let param = variants_param();
+
+        assert_eq!(param.string_to_normalized_value("nonexistent"), None);
+    }
+}